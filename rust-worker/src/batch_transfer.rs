@@ -0,0 +1,159 @@
+use crate::storage::blob_key;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// Git LFS-style batch transfer protocol: the client tells us which oids it
+/// wants to move and in which direction, and we hand back presigned R2 URLs
+/// instead of streaming bytes through the worker. Keeps large translation
+/// bundles off the worker's request/response body limits entirely.
+#[derive(Serialize, Deserialize)]
+pub struct BatchRequest {
+    pub operation: BatchOperation,
+    pub project_id: String,
+    pub branch: String,
+    pub objects: Vec<BatchObject>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchOperation {
+    Upload,
+    Download,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BatchObject {
+    pub oid: String,
+    pub size: u64,
+    pub lang: String,
+    /// Wire format the client packed (or will pack) this object with:
+    /// `"msgpack"` (default) or `"cbor"`. Only meaningful for uploads, where
+    /// it becomes the `Content-Type` hint on the presigned PUT.
+    pub codec: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BatchResponse {
+    pub objects: Vec<BatchObjectResponse>,
+}
+
+#[derive(Serialize)]
+pub struct BatchObjectResponse {
+    pub oid: String,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actions: Option<BatchActions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<BatchError>,
+}
+
+#[derive(Serialize)]
+pub struct BatchActions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload: Option<BatchAction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download: Option<BatchAction>,
+}
+
+#[derive(Serialize)]
+pub struct BatchAction {
+    pub href: String,
+    pub header: std::collections::HashMap<String, String>,
+    pub expires_in: u32,
+}
+
+#[derive(Serialize)]
+pub struct BatchError {
+    pub code: u16,
+    pub message: String,
+}
+
+const URL_EXPIRY_SECS: u32 = 900;
+
+pub async fn handle_batch(mut req: Request, env: &Env, _ctx: &Context) -> Result<Response> {
+    let body: BatchRequest = req.json().await?;
+    let bucket = env.bucket("TRANSLATION_BUCKET")?;
+    let db = env.d1("DB")?;
+    let repo = crate::repo::Repo::new(&db);
+
+    let mut objects = Vec::with_capacity(body.objects.len());
+    for obj in &body.objects {
+        let key = blob_key(&obj.oid);
+
+        if body.operation == BatchOperation::Download {
+            if bucket.get(&key).execute().await?.is_none() {
+                objects.push(BatchObjectResponse {
+                    oid: obj.oid.clone(),
+                    size: obj.size,
+                    actions: None,
+                    error: Some(BatchError {
+                        code: 404,
+                        message: "object does not exist".to_string(),
+                    }),
+                });
+                continue;
+            }
+        }
+
+        // The worker only acts as a control plane here: record what this
+        // oid belongs to so it can later be reconciled against `R2File`,
+        // then hand back a presigned URL for the actual byte transfer.
+        repo.record_batch_object(&obj.oid, obj.size, &body.project_id, &body.branch, &obj.lang)
+            .await?;
+
+        let href = presign_r2_url(env, &key, body.operation == BatchOperation::Upload)?;
+        let codec = crate::codec::Codec::from_field(obj.codec.as_deref())
+            .map_err(worker::Error::RustError)?;
+        let mut header = std::collections::HashMap::new();
+        header.insert("Content-Type".to_string(), codec.content_type().to_string());
+
+        let action = BatchAction {
+            href,
+            header,
+            expires_in: URL_EXPIRY_SECS,
+        };
+        let actions = match body.operation {
+            BatchOperation::Upload => BatchActions {
+                upload: Some(action),
+                download: None,
+            },
+            BatchOperation::Download => BatchActions {
+                upload: None,
+                download: Some(action),
+            },
+        };
+
+        objects.push(BatchObjectResponse {
+            oid: obj.oid.clone(),
+            size: obj.size,
+            actions: Some(actions),
+            error: None,
+        });
+    }
+
+    Response::from_json(&BatchResponse { objects })
+}
+
+/// Builds a presigned R2 URL (AWS SigV4, S3-compatible) for the given key.
+///
+/// R2's S3-compatible API is what makes presigning possible; the `bucket`
+/// binding used elsewhere in this worker has no notion of presigned URLs,
+/// so this talks to R2 via its account-scoped S3 endpoint and credentials
+/// instead of the binding.
+fn presign_r2_url(env: &Env, key: &str, for_upload: bool) -> Result<String> {
+    let account_id = env.secret("R2_ACCOUNT_ID")?.to_string();
+    let bucket_name = env.secret("R2_BUCKET_NAME")?.to_string();
+    let access_key_id = env.secret("R2_ACCESS_KEY_ID")?.to_string();
+    let secret_access_key = env.secret("R2_SECRET_ACCESS_KEY")?.to_string();
+    let method = if for_upload { "PUT" } else { "GET" };
+
+    crate::sigv4::presign_put_or_get(
+        &account_id,
+        &bucket_name,
+        &access_key_id,
+        &secret_access_key,
+        key,
+        method,
+        URL_EXPIRY_SECS,
+    )
+}
@@ -0,0 +1,35 @@
+/// Wire format `FileToUpload::packed_data` was encoded with.
+///
+/// `msgpack` remains the default so existing clients that omit the field
+/// keep working unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    MsgPack,
+    Cbor,
+}
+
+impl Codec {
+    pub fn from_field(codec: Option<&str>) -> std::result::Result<Self, String> {
+        match codec {
+            None | Some("msgpack") => Ok(Codec::MsgPack),
+            Some("cbor") => Ok(Codec::Cbor),
+            Some(other) => Err(format!("unknown codec: {}", other)),
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Codec::MsgPack => "application/msgpack",
+            Codec::Cbor => "application/cbor",
+        }
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> std::result::Result<serde_json::Value, String> {
+        match self {
+            Codec::MsgPack => {
+                rmp_serde::from_slice(bytes).map_err(|e| format!("msgpack: {}", e))
+            }
+            Codec::Cbor => serde_cbor::from_slice(bytes).map_err(|e| format!("cbor: {}", e)),
+        }
+    }
+}
@@ -6,6 +6,14 @@ use sha2::{Digest, Sha256};
 use uuid::Uuid;
 use worker::*;
 
+mod batch_transfer;
+mod codec;
+mod migrate;
+mod process_map;
+mod repo;
+mod sigv4;
+mod storage;
+
 #[derive(Serialize, Deserialize)]
 pub struct HashRequest {
     pub values: Vec<String>,
@@ -56,6 +64,8 @@ pub struct FileToUpload {
     pub contents: serde_json::Value,
     pub source_hash: String,
     pub packed_data: Option<String>,
+    /// Wire format of `packed_data`: `"msgpack"` (default) or `"cbor"`.
+    pub codec: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -106,8 +116,8 @@ fn count_keys_and_bytes(file: &FileToUpload) -> std::result::Result<(usize, usiz
         .ok_or_else(|| "packed_data required".to_string())?;
     let decoded = BASE64.decode(packed).map_err(|e| format!("b64: {}", e))?;
     let bytes = decoded.len();
-    let v: serde_json::Value =
-        rmp_serde::from_slice(&decoded).map_err(|e| format!("msgpack: {}", e))?;
+    let codec = codec::Codec::from_field(file.codec.as_deref())?;
+    let v: serde_json::Value = codec.decode(&decoded)?;
     let keys = v
         .get("raw")
         .and_then(|r| r.as_object().map(|o| o.len()))
@@ -153,11 +163,6 @@ pub fn batch_validate_translations(
         .collect()
 }
 
-fn generate_r2_key(project_id: &str, lang: &str, filename: &str) -> String {
-    let sanitized = filename.replace(['/', '\\'], "-");
-    format!("{}-{}-{}", project_id, lang, sanitized)
-}
-
 pub fn sort_items(items: &mut Vec<serde_json::Value>, sort_by: &str, order: &str) {
     items.sort_by(|a, b| {
         let a_val = &a[sort_by];
@@ -268,99 +273,34 @@ async fn handle_upload(mut req: Request, env: &Env, _ctx: &Context) -> Result<Re
 
     let mut uploaded = Vec::new();
     let mut r2_keys = Vec::new();
-    let mut d1_records = Vec::new();
 
     for f in &upload_req.files {
-        let key = generate_r2_key(&upload_req.project_id, &f.lang, &f.filename);
-        let decoded = BASE64
-            .decode(f.packed_data.as_ref().unwrap())
-            .map_err(|e| {
-                worker::Error::RustError(format!("Failed to decode packed data: {}", e))
-            })?;
-        let main_bytes = decoded.clone();
-        let misc_key: Option<String> = None;
-
-        let mut meta = std::collections::HashMap::new();
-        meta.insert("project".to_string(), upload_req.project_id.clone());
-        meta.insert("lang".to_string(), f.lang.clone());
-        meta.insert("filename".to_string(), f.filename.clone());
-        meta.insert("commitSha".to_string(), upload_req.commit_sha.clone());
-        meta.insert("sourceHash".to_string(), f.source_hash.clone());
-        meta.insert("uploadedAt".to_string(), now.clone());
-
-        bucket
-            .put(&key, main_bytes)
-            .http_metadata(worker::HttpMetadata {
-                content_type: Some("application/msgpack".to_string()),
-                ..Default::default()
-            })
-            .custom_metadata(meta)
-            .execute()
-            .await?;
-
-        uploaded.push(format!("{}/{}", f.lang, f.filename));
-        r2_keys.push(key.clone());
-        if let Some(mk) = misc_key {
-            r2_keys.push(mk);
-        }
+        let map_key = (
+            upload_req.project_id.clone(),
+            upload_req.branch.clone(),
+            f.lang.clone(),
+            f.filename.clone(),
+        );
 
-        let (keys, _) = count_keys_and_bytes(f).unwrap_or((0, 0));
-
-        // Dynamic Pre-cache: Store lightweight metadata in R2 for fast access
-        if let Err(e) = pre_cache_file_metadata(
-            &bucket,
-            &upload_req.project_id,
-            &f.lang,
-            &f.filename,
-            keys,
-            &f.source_hash,
-        )
-        .await
-        {
-            console_log!("Failed to pre-cache metadata for {}: {}", f.filename, e);
-        }
+        let (file_uploaded, file_r2_keys) = match process_map::claim(map_key) {
+            process_map::Claim::Follower(receiver) => process_map::await_result(receiver)
+                .await
+                .map_err(worker::Error::RustError)?,
+            process_map::Claim::Leader(guard) => {
+                let result = upload_one_file(&bucket, &db, &upload_req, f, &now).await;
+                let outcome = result
+                    .as_ref()
+                    .map(|v| v.clone())
+                    .map_err(|e: &worker::Error| e.to_string());
+                guard.complete(outcome);
+                result?
+            }
+        };
 
-        d1_records.push(D1FileRecord {
-            id: Uuid::new_v4().to_string(),
-            project_id: upload_req.project_id.clone(),
-            branch: upload_req.branch.clone(),
-            commit_sha: upload_req.commit_sha.clone(),
-            lang: f.lang.clone(),
-            filename: f.filename.clone(),
-            r2_key: key,
-            source_hash: f.source_hash.clone(),
-            total_keys: keys as i32,
-            uploaded_at: now.clone(),
-            last_updated: now.clone(),
-        });
+        uploaded.extend(file_uploaded);
+        r2_keys.extend(file_r2_keys);
     }
 
-    let values: Vec<String> = d1_records
-        .iter()
-        .map(|r| {
-            format!(
-                "('{}','{}','{}','{}','{}','{}','{}','{}',{},'{}','{}')",
-                r.id,
-                r.project_id,
-                r.branch,
-                r.commit_sha,
-                r.lang,
-                r.filename,
-                r.r2_key,
-                r.source_hash,
-                r.total_keys,
-                r.uploaded_at,
-                r.last_updated
-            )
-        })
-        .collect();
-
-    let sql = format!(
-        "INSERT INTO R2File (id, projectId, branch, commitSha, lang, filename, r2Key, sourceHash, totalKeys, uploadedAt, lastUpdated) VALUES {} ON CONFLICT(projectId, branch, lang, filename) DO UPDATE SET commitSha = excluded.commitSha, r2Key = excluded.r2Key, sourceHash = excluded.sourceHash, totalKeys = excluded.totalKeys, lastUpdated = excluded.lastUpdated",
-        values.join(",")
-    );
-
-    db.prepare(&sql).run().await?;
     Response::from_json(&UploadResponse {
         success: true,
         uploaded_files: uploaded,
@@ -368,6 +308,86 @@ async fn handle_upload(mut req: Request, env: &Env, _ctx: &Context) -> Result<Re
     })
 }
 
+/// Does the actual work for one file: blob store + ref-count bookkeeping +
+/// D1 upsert. Wrapped by a `process_map` guard in `handle_upload` so
+/// concurrent requests for the same upload slot share one run of this.
+async fn upload_one_file(
+    bucket: &Bucket,
+    db: &D1Database,
+    upload_req: &UploadRequest,
+    f: &FileToUpload,
+    now: &str,
+) -> Result<(Vec<String>, Vec<String>)> {
+    let decoded = BASE64
+        .decode(f.packed_data.as_ref().unwrap())
+        .map_err(|e| worker::Error::RustError(format!("Failed to decode packed data: {}", e)))?;
+    let codec = codec::Codec::from_field(f.codec.as_deref()).map_err(worker::Error::RustError)?;
+    let hash = storage::full_hash_value(&decoded);
+    let key = storage::blob_key(&hash);
+    let misc_key: Option<String> = None;
+    let repo = repo::Repo::new(db);
+
+    // If this (project, branch, lang, filename) slot previously pointed at a
+    // different blob, its ref will be dropped once the upsert below lands.
+    let previous_hash = repo
+        .find_r2_key(&upload_req.project_id, &upload_req.branch, &f.lang, &f.filename)
+        .await?
+        .and_then(|prev_key| prev_key.strip_prefix("blob/").map(|h| h.to_string()));
+
+    storage::store_blob(bucket, &hash, decoded, codec.content_type()).await?;
+
+    // This row only gains a *new* reference to `hash` when it didn't already
+    // point at it — re-uploading unchanged bytes to the same slot must not
+    // inflate the ref count, or it'll never reach zero for cleanup.
+    let is_new_reference = previous_hash.as_deref() != Some(hash.as_str());
+    if is_new_reference {
+        storage::increment_blob_ref(db, &hash).await?;
+        if let Some(prev_hash) = previous_hash {
+            storage::release_blob_ref(db, &prev_hash).await?;
+        }
+    }
+
+    let mut uploaded = vec![format!("{}/{}", f.lang, f.filename)];
+    let mut r2_keys = vec![key.clone()];
+    if let Some(mk) = misc_key {
+        r2_keys.push(mk);
+    }
+
+    let (keys, _) = count_keys_and_bytes(f).unwrap_or((0, 0));
+
+    // Dynamic Pre-cache: Store lightweight metadata in R2 for fast access
+    if let Err(e) = pre_cache_file_metadata(
+        bucket,
+        &upload_req.project_id,
+        &f.lang,
+        &f.filename,
+        keys,
+        &f.source_hash,
+    )
+    .await
+    {
+        console_log!("Failed to pre-cache metadata for {}: {}", f.filename, e);
+    }
+
+    let record = D1FileRecord {
+        id: Uuid::new_v4().to_string(),
+        project_id: upload_req.project_id.clone(),
+        branch: upload_req.branch.clone(),
+        commit_sha: upload_req.commit_sha.clone(),
+        lang: f.lang.clone(),
+        filename: f.filename.clone(),
+        r2_key: key,
+        source_hash: f.source_hash.clone(),
+        total_keys: keys as i32,
+        uploaded_at: now.to_string(),
+        last_updated: now.to_string(),
+    };
+
+    repo.insert_file_records(std::slice::from_ref(&record)).await?;
+
+    Ok((uploaded, r2_keys))
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct MiscGitRequest {
     pub project_id: String,
@@ -407,15 +427,9 @@ async fn handle_upload_misc_git(mut req: Request, env: &Env, _ctx: &Context) ->
     // Also persist misc r2 key to D1 so cleanup and tooling know the exact misc object name.
     // This is best-effort: log warnings on failure but do not fail the request.
     if let Ok(db) = env.d1("DB") {
-        let update_sql = format!(
-            "UPDATE R2File SET miscR2Key = '{}' WHERE r2Key = '{}'",
-            key.replace("'", "''"),
-            body.r2_key.replace("'", "''")
-        );
-        match db.prepare(&update_sql).run().await {
-            Ok(_) => {
+        match repo::Repo::new(&db).set_misc_key(&body.r2_key, &key).await {
+            Ok(()) => {
                 // updated (or no-op if no matching row)
-                // nothing else to do
             }
             Err(e) => {
                 // Log but continue
@@ -437,6 +451,8 @@ async fn main(mut req: Request, env: Env, ctx: Context) -> Result<Response> {
             // DEPRECATED: This endpoint is deprecated in favor of fetching files directly from GitHub
             handle_upload(req, &env, &ctx).await
         }
+        (Method::Post, "/objects/batch") => batch_transfer::handle_batch(req, &env, &ctx).await,
+        (Method::Post, "/migrate") => migrate::handle_migrate(req, &env, &ctx).await,
         (Method::Post, "/upload-misc-git") => {
             // DEPRECATED: This endpoint is deprecated in favor of fetching files directly from GitHub
             handle_upload_misc_git(req, &env, &ctx).await
@@ -559,12 +575,36 @@ mod tests {
             contents: serde_json::json!({}),
             source_hash: "".to_string(),
             packed_data: Some(packed_b64),
+            codec: None,
         };
         let (keys, bytes) = count_keys_and_bytes(&file).expect("should parse packed data");
         assert_eq!(keys, 2);
         assert_eq!(bytes, packed.len());
     }
 
+    #[test]
+    fn test_count_keys_and_bytes_cbor() {
+        let payload = serde_json::json!({ "raw": {"k1": "v1", "k2": "v2"} });
+        let packed = serde_cbor::to_vec(&payload).unwrap();
+        let packed_b64 = BASE64.encode(&packed);
+        let file = FileToUpload {
+            lang: "en".to_string(),
+            filename: "common.json".to_string(),
+            contents: serde_json::json!({}),
+            source_hash: "".to_string(),
+            packed_data: Some(packed_b64),
+            codec: Some("cbor".to_string()),
+        };
+        let (keys, bytes) = count_keys_and_bytes(&file).expect("should parse cbor packed data");
+        assert_eq!(keys, 2);
+        assert_eq!(bytes, packed.len());
+    }
+
+    #[test]
+    fn test_codec_from_field_rejects_unknown() {
+        assert!(codec::Codec::from_field(Some("protobuf")).is_err());
+    }
+
     #[test]
     fn test_count_keys_and_bytes_contents() {
         let payload = serde_json::json!({ "raw": {"a": "A", "b": "B"} });
@@ -576,6 +616,7 @@ mod tests {
             contents: serde_json::json!({}),
             source_hash: "".to_string(),
             packed_data: Some(packed_b64),
+            codec: None,
         };
         let (keys, bytes) =
             count_keys_and_bytes(&file).expect("should parse contents via packed_data");
@@ -598,6 +639,7 @@ mod tests {
             contents: serde_json::Value::Object(serde_json::Map::new()),
             source_hash: "".to_string(),
             packed_data: Some(packed_b64),
+            codec: None,
         };
         let (keys, _) = count_keys_and_bytes(&file).expect("should parse big file");
         assert_eq!(keys, 10001);
@@ -0,0 +1,168 @@
+use crate::storage;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// Resumable, idempotent migration of `R2File` rows from one R2 key scheme
+/// to another (e.g. flat `project-lang-filename` keys to content-addressed
+/// `blob/<hash>` keys). Each call processes one bounded batch and hands
+/// back a cursor so the caller can keep invoking `/migrate` until it's done,
+/// surviving individual worker invocation limits.
+///
+/// The destination key isn't read from anywhere — it's derived from the
+/// object's own content the same way `storage::store_blob` derives it on
+/// upload, so this subsystem performs the actual cutover rather than
+/// assuming some other process already computed it.
+const BATCH_SIZE: u32 = 50;
+
+#[derive(Serialize, Deserialize)]
+pub struct MigrateRequest {
+    /// Row id to resume after; omit to start from the beginning.
+    pub cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct MigrateResponse {
+    pub migrated: u32,
+    pub failed: u32,
+    /// Cursor to pass on the next call; `None` once every row has a
+    /// `migrated_at` marker.
+    pub next_cursor: Option<String>,
+    pub done: bool,
+}
+
+struct PendingRow {
+    id: String,
+    old_key: String,
+    misc_old_key: Option<String>,
+}
+
+pub async fn handle_migrate(mut req: Request, env: &Env, _ctx: &Context) -> Result<Response> {
+    let body: MigrateRequest = req.json().await.unwrap_or(MigrateRequest { cursor: None });
+    let db = env.d1("DB")?;
+    let bucket = env.bucket("TRANSLATION_BUCKET")?;
+
+    let rows = fetch_batch(&db, body.cursor.as_deref()).await?;
+    let mut migrated = 0u32;
+    let mut failed = 0u32;
+    let mut last_id = None;
+
+    for row in &rows {
+        last_id = Some(row.id.clone());
+        match migrate_one(&bucket, &db, row).await {
+            Ok(()) => migrated += 1,
+            Err(e) => {
+                // Leave the row un-migrated for retry on the next pass; the
+                // source object is untouched so the store stays readable.
+                console_log!("[migrate] failed to migrate {}: {}", row.id, e);
+                failed += 1;
+            }
+        }
+    }
+
+    let done = (rows.len() as u32) < BATCH_SIZE;
+    Response::from_json(&MigrateResponse {
+        migrated,
+        failed,
+        next_cursor: if done { None } else { last_id },
+        done,
+    })
+}
+
+async fn fetch_batch(db: &D1Database, cursor: Option<&str>) -> Result<Vec<PendingRow>> {
+    let stmt = match cursor {
+        Some(after_id) => db
+            .prepare(
+                "SELECT id, r2Key, miscR2Key FROM R2File \
+                 WHERE migratedAt IS NULL AND id > ?1 \
+                 ORDER BY id LIMIT ?2",
+            )
+            .bind(&[after_id.into(), BATCH_SIZE.into()])?,
+        None => db
+            .prepare(
+                "SELECT id, r2Key, miscR2Key FROM R2File \
+                 WHERE migratedAt IS NULL \
+                 ORDER BY id LIMIT ?1",
+            )
+            .bind(&[BATCH_SIZE.into()])?,
+    };
+
+    #[derive(Deserialize)]
+    struct Row {
+        id: String,
+        #[serde(rename = "r2Key")]
+        r2_key: String,
+        #[serde(rename = "miscR2Key")]
+        misc_r2_key: Option<String>,
+    }
+
+    let results = stmt.all().await?;
+    let rows: Vec<Row> = results.results()?;
+    Ok(rows
+        .into_iter()
+        .map(|r| PendingRow {
+            id: r.id,
+            old_key: r.r2_key,
+            misc_old_key: r.misc_r2_key,
+        })
+        .collect())
+}
+
+async fn migrate_one(bucket: &Bucket, db: &D1Database, row: &PendingRow) -> Result<()> {
+    // Copy before touching D1 or deleting anything: an interrupted migration
+    // must never leave a row pointing at a key nobody wrote.
+    let new_key = copy_object(bucket, &row.old_key).await?;
+    let new_misc_key = match &row.misc_old_key {
+        Some(misc_old_key) => Some(copy_object(bucket, misc_old_key).await?),
+        None => None,
+    };
+
+    db.prepare("UPDATE R2File SET r2Key = ?1, miscR2Key = ?2, migratedAt = ?3 WHERE id = ?4")
+        .bind(&[
+            new_key.into(),
+            new_misc_key.into(),
+            chrono::Utc::now().to_rfc3339().into(),
+            row.id.clone().into(),
+        ])?
+        .run()
+        .await?;
+
+    // Old objects are left in place; a separate cleanup pass (or the blob
+    // ref-count orphan sweep) reclaims them once nothing points at them
+    // anymore.
+    Ok(())
+}
+
+/// Copies the object at `old_key` to its content-addressed destination key
+/// (derived from the bytes themselves, mirroring `storage::store_blob`) and
+/// returns that destination key. A no-op put if `old_key` is already at its
+/// destination, which keeps re-running a batch idempotent.
+async fn copy_object(bucket: &Bucket, old_key: &str) -> Result<String> {
+    let object = bucket
+        .get(old_key)
+        .execute()
+        .await?
+        .ok_or_else(|| worker::Error::RustError(format!("source object missing: {}", old_key)))?;
+    let content_type = object
+        .http_metadata()
+        .content_type
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let bytes = object
+        .body()
+        .ok_or_else(|| worker::Error::RustError("source object has no body".to_string()))?
+        .bytes()
+        .await?;
+
+    let new_key = storage::blob_key(&storage::full_hash_value(&bytes));
+    if new_key != old_key {
+        bucket
+            .put(&new_key, bytes)
+            .http_metadata(worker::HttpMetadata {
+                content_type: Some(content_type),
+                ..Default::default()
+            })
+            .execute()
+            .await?;
+    }
+
+    Ok(new_key)
+}
@@ -0,0 +1,135 @@
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use tokio::sync::watch;
+
+/// Coalesces concurrent uploads that target the same `(project_id, branch,
+/// lang, filename)` slot onto a single R2 put + D1 write.
+///
+/// Cloudflare reuses worker isolates across requests, so without this a
+/// burst of identical uploads can race the `ON CONFLICT` upsert and double
+/// the R2 puts. Adapted from pict-rs's `ProcessMap`: the first request for a
+/// key does the work and broadcasts the result; latecomers just await it.
+///
+/// Uses `watch` rather than a queued channel: a `flume`/`mpsc` channel only
+/// delivers a sent value to one of its cloned receivers, which is wrong
+/// here since every follower needs to see the same result. `watch` holds
+/// the latest value and every clone can read it.
+pub type UploadKey = (String, String, String, String);
+pub type UploadResult = Result<(Vec<String>, Vec<String>), String>;
+
+static PROCESS_MAP: Lazy<DashMap<UploadKey, watch::Receiver<Option<UploadResult>>>> =
+    Lazy::new(DashMap::new);
+
+/// Either claims leadership for `key` (returning a `Guard` the caller must
+/// eventually complete) or finds an in-flight request and returns a
+/// receiver that resolves once the leader finishes.
+pub enum Claim {
+    Leader(Guard),
+    Follower(watch::Receiver<Option<UploadResult>>),
+}
+
+/// Held by the request that's actually doing the upload. Dropping it (on
+/// any path, including early-return on error) removes the map entry so a
+/// failed upload never wedges the key for everyone else.
+pub struct Guard {
+    key: UploadKey,
+    sender: Option<watch::Sender<Option<UploadResult>>>,
+}
+
+impl Guard {
+    pub fn complete(mut self, result: UploadResult) {
+        if let Some(sender) = self.sender.take() {
+            let _ = sender.send(Some(result));
+        }
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        PROCESS_MAP.remove(&self.key);
+        // If `complete` was never called (e.g. a panic unwound past it),
+        // the watch channel is dropped with no value sent and waiting
+        // followers see `changed()` fail rather than hang forever.
+    }
+}
+
+pub fn claim(key: UploadKey) -> Claim {
+    if let Some(existing) = PROCESS_MAP.get(&key) {
+        return Claim::Follower(existing.clone());
+    }
+
+    let (sender, receiver) = watch::channel(None);
+    match PROCESS_MAP.entry(key.clone()) {
+        dashmap::mapref::entry::Entry::Vacant(entry) => {
+            entry.insert(receiver);
+            Claim::Leader(Guard {
+                key,
+                sender: Some(sender),
+            })
+        }
+        dashmap::mapref::entry::Entry::Occupied(entry) => Claim::Follower(entry.get().clone()),
+    }
+}
+
+pub async fn await_result(mut receiver: watch::Receiver<Option<UploadResult>>) -> UploadResult {
+    loop {
+        if let Some(result) = receiver.borrow().clone() {
+            return result;
+        }
+        if receiver.changed().await.is_err() {
+            return Err("upload leader dropped without completing".to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> UploadKey {
+        (name.to_string(), "main".to_string(), "en".to_string(), "a.json".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_followers_observe_the_leaders_result() {
+        let key = key("followers-observe-result");
+
+        let guard = match claim(key.clone()) {
+            Claim::Leader(guard) => guard,
+            Claim::Follower(_) => panic!("expected to win leadership on a fresh key"),
+        };
+
+        let mut followers = Vec::new();
+        for _ in 0..2 {
+            match claim(key.clone()) {
+                Claim::Follower(receiver) => followers.push(tokio::spawn(await_result(receiver))),
+                Claim::Leader(_) => panic!("only one claimant should win leadership"),
+            }
+        }
+
+        guard.complete(Ok((vec!["a".to_string()], vec!["blob/a".to_string()])));
+
+        for follower in followers {
+            let result = follower.await.expect("follower task should not panic");
+            assert_eq!(result, Ok((vec!["a".to_string()], vec!["blob/a".to_string()])));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dropping_guard_without_completing_unblocks_followers_with_error() {
+        let key = key("dropped-guard-unblocks-followers");
+
+        let guard = match claim(key.clone()) {
+            Claim::Leader(guard) => guard,
+            Claim::Follower(_) => panic!("expected to win leadership on a fresh key"),
+        };
+        let receiver = match claim(key.clone()) {
+            Claim::Follower(receiver) => receiver,
+            Claim::Leader(_) => panic!("only one claimant should win leadership"),
+        };
+
+        drop(guard);
+
+        assert!(await_result(receiver).await.is_err());
+    }
+}
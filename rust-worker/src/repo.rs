@@ -0,0 +1,108 @@
+use crate::D1FileRecord;
+use worker::*;
+
+/// Thin, typed wrapper around the `DB` binding. Every statement here binds
+/// its values positionally instead of interpolating them into the SQL
+/// string, so a filename or commit sha containing `'` can't break (or
+/// inject into) a query.
+pub struct Repo<'a> {
+    db: &'a D1Database,
+}
+
+impl<'a> Repo<'a> {
+    pub fn new(db: &'a D1Database) -> Self {
+        Self { db }
+    }
+
+    /// Upserts one or more `R2File` rows in a single D1 batch round trip.
+    pub async fn insert_file_records(&self, records: &[D1FileRecord]) -> Result<()> {
+        let mut statements = Vec::with_capacity(records.len());
+        for r in records {
+            let stmt = self
+                .db
+                .prepare(
+                    "INSERT INTO R2File (id, projectId, branch, commitSha, lang, filename, r2Key, sourceHash, totalKeys, uploadedAt, lastUpdated) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11) \
+                     ON CONFLICT(projectId, branch, lang, filename) DO UPDATE SET \
+                     commitSha = excluded.commitSha, r2Key = excluded.r2Key, sourceHash = excluded.sourceHash, \
+                     totalKeys = excluded.totalKeys, lastUpdated = excluded.lastUpdated",
+                )
+                .bind(&[
+                    r.id.clone().into(),
+                    r.project_id.clone().into(),
+                    r.branch.clone().into(),
+                    r.commit_sha.clone().into(),
+                    r.lang.clone().into(),
+                    r.filename.clone().into(),
+                    r.r2_key.clone().into(),
+                    r.source_hash.clone().into(),
+                    r.total_keys.into(),
+                    r.uploaded_at.clone().into(),
+                    r.last_updated.clone().into(),
+                ])?;
+            statements.push(stmt);
+        }
+
+        self.db.batch(statements).await?;
+        Ok(())
+    }
+
+    /// Looks up the R2 key a given upload slot currently points at, if any.
+    pub async fn find_r2_key(
+        &self,
+        project_id: &str,
+        branch: &str,
+        lang: &str,
+        filename: &str,
+    ) -> Result<Option<String>> {
+        self.db
+            .prepare(
+                "SELECT r2Key FROM R2File WHERE projectId = ?1 AND branch = ?2 AND lang = ?3 AND filename = ?4",
+            )
+            .bind(&[project_id.into(), branch.into(), lang.into(), filename.into()])?
+            .first::<String>(Some("r2Key"))
+            .await
+    }
+
+    /// Records the metadata the LFS-style `/objects/batch` endpoint knows
+    /// about an oid before handing back a presigned URL. The worker never
+    /// sees the object's bytes in that flow, so this is the only place its
+    /// existence gets tracked until a future upload/migration reconciles it
+    /// against `R2File`.
+    pub async fn record_batch_object(
+        &self,
+        oid: &str,
+        size: u64,
+        project_id: &str,
+        branch: &str,
+        lang: &str,
+    ) -> Result<()> {
+        self.db
+            .prepare(
+                "INSERT INTO BatchObject (oid, size, projectId, branch, lang, recordedAt) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6) \
+                 ON CONFLICT(oid) DO UPDATE SET size = excluded.size, projectId = excluded.projectId, \
+                 branch = excluded.branch, lang = excluded.lang, recordedAt = excluded.recordedAt",
+            )
+            .bind(&[
+                oid.into(),
+                (size as i64).into(),
+                project_id.into(),
+                branch.into(),
+                lang.into(),
+                chrono::Utc::now().to_rfc3339().into(),
+            ])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_misc_key(&self, r2_key: &str, misc_key: &str) -> Result<()> {
+        self.db
+            .prepare("UPDATE R2File SET miscR2Key = ?1 WHERE r2Key = ?2")
+            .bind(&[misc_key.into(), r2_key.into()])?
+            .run()
+            .await?;
+        Ok(())
+    }
+}
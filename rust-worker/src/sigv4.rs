@@ -0,0 +1,168 @@
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use worker::Result;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const R2_REGION: &str = "auto";
+const R2_SERVICE: &str = "s3";
+
+/// Presigns an S3-compatible (R2) URL for either a PUT or a GET. SigV4 signs
+/// the HTTP method as the first line of the canonical request, so `method`
+/// must match whatever verb the caller will actually issue against `href`.
+pub fn presign_put_or_get(
+    account_id: &str,
+    bucket: &str,
+    access_key_id: &str,
+    secret_access_key: &str,
+    key: &str,
+    method: &str,
+    expires_in_secs: u32,
+) -> Result<String> {
+    let (date_stamp, amz_date) = timestamp()?;
+    Ok(presign_with_timestamp(
+        account_id,
+        bucket,
+        access_key_id,
+        secret_access_key,
+        key,
+        method,
+        expires_in_secs,
+        &date_stamp,
+        &amz_date,
+    ))
+}
+
+/// Does the actual SigV4 presigning given an explicit timestamp, so the
+/// deterministic string-building logic can be unit tested without touching
+/// the clock. `presign_put_or_get` is the only real caller.
+fn presign_with_timestamp(
+    account_id: &str,
+    bucket: &str,
+    access_key_id: &str,
+    secret_access_key: &str,
+    key: &str,
+    method: &str,
+    expires_in_secs: u32,
+    date_stamp: &str,
+    amz_date: &str,
+) -> String {
+    let host = format!("{}.r2.cloudflarestorage.com", account_id);
+    let credential_scope = format!("{}/{}/{}/aws4_request", date_stamp, R2_REGION, R2_SERVICE);
+    let credential = format!("{}/{}", access_key_id, credential_scope);
+
+    let mut query = vec![
+        ("X-Amz-Algorithm".to_string(), "AWS4-HMAC-SHA256".to_string()),
+        ("X-Amz-Credential".to_string(), urlencode(&credential)),
+        ("X-Amz-Date".to_string(), amz_date.clone()),
+        ("X-Amz-Expires".to_string(), expires_in_secs.to_string()),
+        ("X-Amz-SignedHeaders".to_string(), "host".to_string()),
+    ];
+    query.sort();
+
+    let canonical_query = query
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let canonical_uri = format!("/{}/{}", bucket, key);
+    let canonical_request = format!(
+        "{}\n{}\n{}\nhost:{}\n\nhost\nUNSIGNED-PAYLOAD",
+        method, canonical_uri, canonical_query, host
+    );
+    let canonical_request_hash = hex::encode(Sha256::digest(canonical_request.as_bytes()));
+
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date, credential_scope, canonical_request_hash
+    );
+
+    let signing_key = derive_signing_key(secret_access_key, date_stamp);
+    let signature = hex::encode(hmac(&signing_key, string_to_sign.as_bytes()));
+
+    format!(
+        "https://{}{}?{}&X-Amz-Signature={}",
+        host, canonical_uri, canonical_query, signature
+    )
+}
+
+fn derive_signing_key(secret_access_key: &str, date_stamp: &str) -> Vec<u8> {
+    let k_date = hmac(format!("AWS4{}", secret_access_key).as_bytes(), date_stamp.as_bytes());
+    let k_region = hmac(&k_date, R2_REGION.as_bytes());
+    let k_service = hmac(&k_region, R2_SERVICE.as_bytes());
+    hmac(&k_service, b"aws4_request")
+}
+
+fn hmac(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn timestamp() -> Result<(String, String)> {
+    let now = chrono::Utc::now();
+    Ok((now.format("%Y%m%d").to_string(), now.format("%Y%m%dT%H%M%SZ").to_string()))
+}
+
+fn urlencode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn presign(method: &str) -> String {
+        presign_with_timestamp(
+            "account123",
+            "my-bucket",
+            "AKIAEXAMPLE",
+            "secretexample",
+            "blob/abc123",
+            method,
+            900,
+            "20260726",
+            "20260726T120000Z",
+        )
+    }
+
+    #[test]
+    fn test_presign_includes_expected_query_shape() {
+        let url = presign("GET");
+        assert!(url.starts_with("https://account123.r2.cloudflarestorage.com/my-bucket/blob/abc123?"));
+        assert!(url.contains("X-Amz-Algorithm=AWS4-HMAC-SHA256"));
+        assert!(url.contains("X-Amz-Date=20260726T120000Z"));
+        assert!(url.contains("X-Amz-Expires=900"));
+        assert!(url.contains("X-Amz-Credential=AKIAEXAMPLE%2F20260726%2Fauto%2Fs3%2Faws4_request"));
+        assert!(url.contains("X-Amz-SignedHeaders=host"));
+        assert!(url.contains("X-Amz-Signature="));
+    }
+
+    #[test]
+    fn test_put_and_get_presigns_produce_different_signatures() {
+        let get_url = presign("GET");
+        let put_url = presign("PUT");
+
+        let get_sig = get_url.rsplit("X-Amz-Signature=").next().unwrap();
+        let put_sig = put_url.rsplit("X-Amz-Signature=").next().unwrap();
+
+        assert!(!get_sig.is_empty());
+        assert!(!put_sig.is_empty());
+        assert_ne!(get_sig, put_sig);
+    }
+
+    #[test]
+    fn test_presign_is_deterministic_for_same_inputs() {
+        assert_eq!(presign("GET"), presign("GET"));
+    }
+}
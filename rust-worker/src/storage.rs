@@ -0,0 +1,102 @@
+use sha2::{Digest, Sha256};
+use worker::*;
+
+/// Full-width content hash for content-addressed storage.
+///
+/// `hash_value` in `lib.rs` truncates to 64 bits for cheap translation-key
+/// hashing; that's too collision-prone to key shared blob storage on, so
+/// blobs are addressed by the complete 256-bit SHA-256 digest instead.
+pub fn full_hash_value(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    hex::encode(hasher.finalize())
+}
+
+/// R2 key for a content-addressed blob.
+pub fn blob_key(hash: &str) -> String {
+    format!("blob/{}", hash)
+}
+
+/// Puts `bytes` under the blob's content-addressed key unless an object
+/// already exists there. Does not touch `BlobRef` — call `increment_blob_ref`
+/// separately, and only when this upload actually establishes a new
+/// reference to the blob (see its doc comment).
+///
+/// Returns `true` if a new R2 object was written, `false` if an existing
+/// blob was reused.
+pub async fn store_blob(
+    bucket: &Bucket,
+    hash: &str,
+    bytes: Vec<u8>,
+    content_type: &str,
+) -> Result<bool> {
+    let key = blob_key(hash);
+    let newly_created = bucket.get(&key).execute().await?.is_none();
+
+    if newly_created {
+        bucket
+            .put(&key, bytes)
+            .http_metadata(worker::HttpMetadata {
+                content_type: Some(content_type.to_string()),
+                ..Default::default()
+            })
+            .execute()
+            .await?;
+    }
+
+    Ok(newly_created)
+}
+
+/// Bumps (or creates) a blob's `BlobRef` row.
+///
+/// Must be called exactly once per *row* that references the blob, not once
+/// per upload: re-uploading unchanged bytes to a slot that already pointed
+/// at this hash doesn't create a new reference, so callers only invoke this
+/// when a slot is newly created or is switching from a different hash.
+pub async fn increment_blob_ref(db: &D1Database, hash: &str) -> Result<()> {
+    db.prepare(
+        "INSERT INTO BlobRef (hash, refCount) VALUES (?1, 1) \
+         ON CONFLICT(hash) DO UPDATE SET refCount = refCount + 1",
+    )
+    .bind(&[hash.into()])?
+    .run()
+    .await?;
+
+    Ok(())
+}
+
+/// Decrements a blob's ref count, enqueueing it for orphan cleanup once the
+/// count reaches zero. Called when a `R2File` row that pointed at `hash` is
+/// overwritten or deleted.
+///
+/// The decrement and the zero-check happen in one `RETURNING` statement so
+/// that of two concurrent releases of the same hash, only the one that
+/// actually drives the count to zero enqueues a deletion — a separate
+/// decrement-then-select would let both observe `refCount == 0` and
+/// double-enqueue.
+pub async fn release_blob_ref(db: &D1Database, hash: &str) -> Result<()> {
+    let remaining = db
+        .prepare("UPDATE BlobRef SET refCount = refCount - 1 WHERE hash = ?1 RETURNING refCount")
+        .bind(&[hash.into()])?
+        .first::<i64>(Some("refCount"))
+        .await?;
+
+    if remaining == Some(0) {
+        // ON CONFLICT DO NOTHING: even if this hash is somehow already
+        // queued, re-enqueueing it is never more correct than leaving the
+        // existing entry alone.
+        db.prepare(
+            "INSERT INTO BlobDeletionQueue (hash, r2Key, enqueuedAt) VALUES (?1, ?2, ?3) \
+             ON CONFLICT(hash) DO NOTHING",
+        )
+        .bind(&[
+            hash.into(),
+            blob_key(hash).into(),
+            chrono::Utc::now().to_rfc3339().into(),
+        ])?
+        .run()
+        .await?;
+    }
+
+    Ok(())
+}